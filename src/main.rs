@@ -1,16 +1,30 @@
 // Import standard library modules
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::sync::mpsc;
-use std::{collections::HashSet, fs, io, path::Path};
+use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 // External crates
-use chrono::{DateTime, Local}; // For handling file modified date/time
+use chrono::{DateTime, Local, NaiveDate}; // For handling file modified date/time
 use clap::{Arg, Command};
+use indexmap::IndexMap;
 use notify::{
-    event::ModifyKind, Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::ModifyKind, Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode,
+    Watcher, WatcherKind,
 };
-use sha2::{Digest, Sha256};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+/// Number of bytes read from the start and end of a file when computing the
+/// cheap partial fingerprint used to narrow down same-size candidates.
+const PARTIAL_FINGERPRINT_CHUNK: usize = 4096;
 
 fn main() -> io::Result<()> {
     // CLI argument parsing using clap
@@ -31,102 +45,535 @@ fn main() -> io::Result<()> {
                 .num_args(0)
                 .help("Enable watch mode to auto-organize new files."),
         )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .default_value(DEFAULT_DEST_TEMPLATE)
+                .help(
+                    "Destination template, e.g. \"{category}/{year}/{month:long}/{ext}\". \
+                     Placeholders: {category} {ext} {year} {month} {month:long} {day} {name}.",
+                ),
+        )
+        .arg(
+            Arg::new("date-source")
+                .long("date-source")
+                .default_value("filename-then-mtime")
+                .value_parser(["filename", "mtime", "filename-then-mtime"])
+                .help("Where to pull the date folder from: filename, mtime, or filename-then-mtime."),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help(
+                    "Path to a TOML/JSON file mapping category -> [extension regexes], \
+                     plus an optional allowlist. Falls back to the built-in categories if unset.",
+                ),
+        )
+        .arg(
+            Arg::new("poll-interval")
+                .long("poll-interval")
+                .default_value("2")
+                .value_parser(clap::value_parser!(u64))
+                .help(
+                    "Polling interval in seconds, used only when the native watch backend \
+                     (inotify/FSEvents/etc.) isn't available and FileOrganizer falls back to polling.",
+                ),
+        )
+        .arg(
+            Arg::new("db")
+                .long("db")
+                .help(
+                    "Path to the SQLite dedup index. Defaults to a \"<path>.fileorganizer.db\" \
+                     file next to the organized folder (not inside it).",
+                ),
+        )
         .get_matches();
 
     // Extract values from the parsed CLI arguments
     let folder_path = matches.get_one::<String>("path").unwrap();
     let watch_mode = matches.get_flag("watch");
+    let format_template = matches.get_one::<String>("format").unwrap();
+    let date_source = DateSource::from_arg(matches.get_one::<String>("date-source").unwrap());
+    let classifier = match matches.get_one::<String>("config") {
+        Some(path) => Some(load_classifier(path)?),
+        None => None,
+    };
+    let db_path = matches
+        .get_one::<String>("db")
+        .cloned()
+        .unwrap_or_else(|| format!("{}.fileorganizer.db", folder_path.trim_end_matches('/')));
+    let index = DedupIndex::open(Path::new(&db_path)).map_err(sqlite_err_to_io)?;
 
     //  Step 1: Organize all existing files once
-    organize_files(folder_path)?;
+    organize_files(
+        folder_path,
+        format_template,
+        date_source,
+        classifier.as_ref(),
+        Some(&index),
+    )?;
 
     //  Step 2: If watch mode is enabled, keep watching for new files
     if watch_mode {
+        let poll_interval = *matches.get_one::<u64>("poll-interval").unwrap();
         println!("Watching for new files in {}", folder_path);
-        watch_folder(folder_path)?;
+        watch_folder(
+            folder_path,
+            format_template,
+            date_source,
+            classifier.as_ref(),
+            poll_interval,
+            Some(&index),
+        )?;
     }
 
     Ok(())
 }
 
+/// Where the date used for the destination's `{year}`/`{month}`/`{day}`
+/// placeholders should come from.
+#[derive(Clone, Copy)]
+enum DateSource {
+    /// Parse the date out of the filename; if nothing matches, the file is
+    /// filed under an `unknown-date` folder instead of guessing from mtime.
+    Filename,
+    /// Always use `metadata.modified()`, ignoring the filename entirely.
+    Mtime,
+    /// Try the filename first, falling back to mtime when it doesn't match.
+    FilenameThenMtime,
+}
+
+impl DateSource {
+    fn from_arg(value: &str) -> Self {
+        match value {
+            "filename" => DateSource::Filename,
+            "mtime" => DateSource::Mtime,
+            _ => DateSource::FilenameThenMtime,
+        }
+    }
+}
+
+/// Default destination template — matches the folder layout FileOrganizer
+/// has always used (`<category>/<year>-<month>-<day>`), so leaving
+/// `--format` unset preserves today's behavior.
+const DEFAULT_DEST_TEMPLATE: &str = "{category}/{year}-{month}-{day}";
+
+/// On-disk shape of a `--config` file: a category name mapped to the
+/// extension regexes that belong to it, plus an optional allowlist. TOML
+/// and JSON are both accepted — the file extension picks the parser.
+///
+/// `categories` uses `IndexMap` rather than `HashMap` so rule precedence
+/// follows the order categories are written in the file — a plain
+/// `HashMap` would randomize iteration order per-process, making "first
+/// match wins" non-deterministic for configs with overlapping patterns.
+#[derive(Deserialize)]
+struct OrganizerConfig {
+    #[serde(default)]
+    categories: IndexMap<String, Vec<String>>,
+    #[serde(default)]
+    allowlist: Option<Vec<String>>,
+}
+
+/// Compiled form of an `OrganizerConfig`, ready to classify extensions
+/// without re-parsing regexes per file.
+struct Classifier {
+    rules: Vec<(String, Regex)>,
+    allowlist: Option<HashSet<String>>,
+}
+
+/// Loads and compiles a `--config` file. The format (TOML vs JSON) is
+/// picked from the file extension, defaulting to TOML for anything else.
+fn load_classifier(path: &str) -> io::Result<Classifier> {
+    let contents = fs::read_to_string(path)?;
+    let config: OrganizerConfig = if path.ends_with(".json") {
+        serde_json::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else {
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    };
+
+    let mut rules = Vec::new();
+    for (category, patterns) in config.categories {
+        for pattern in patterns {
+            let regex =
+                Regex::new(&pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            rules.push((category.clone(), regex));
+        }
+    }
+
+    let allowlist = config
+        .allowlist
+        .map(|extensions| extensions.into_iter().map(|ext| ext.to_lowercase()).collect());
+
+    Ok(Classifier { rules, allowlist })
+}
+
+/// Classifies an extension into a category name. With a `Classifier`
+/// loaded from `--config`, an extension outside its optional allowlist is
+/// left untouched (`None`); otherwise it falls through to the first
+/// matching regex rule, or `"others"` if nothing matches. Without a
+/// classifier, falls back to the built-in extension table.
+fn classify_extension(classifier: Option<&Classifier>, extension: &str) -> Option<String> {
+    match classifier {
+        Some(classifier) => {
+            if let Some(allowlist) = &classifier.allowlist {
+                if !allowlist.contains(extension) {
+                    return None;
+                }
+            }
+            let category = classifier
+                .rules
+                .iter()
+                .find(|(_, regex)| regex.is_match(extension))
+                .map(|(category, _)| category.clone())
+                .unwrap_or_else(|| "others".to_string());
+            Some(category)
+        }
+        None => Some(builtin_category(extension).to_string()),
+    }
+}
+
+/// The category table FileOrganizer shipped with before `--config` existed;
+/// still the default when no config file is given.
+fn builtin_category(extension: &str) -> &'static str {
+    match extension {
+        "jpg" | "jpeg" | "png" | "bmp" | "tiff" => "images",
+        "gif" => "gifs",
+        "mp4" | "mov" | "avi" | "mkv" => "videos",
+        "mp3" | "wav" | "flac" => "audio",
+        "pdf" | "docx" | "txt" => "documents",
+        "zip" | "rar" | "7z" => "archives",
+        _ => "others",
+    }
+}
+
+/// A persistent dedup index backed by SQLite, storing one row per organized
+/// file: `(canonical_path, size, hash, last_modified)`. Surviving across
+/// runs lets the organizer recognize duplicates of files it already moved
+/// in a previous session, and lets watch mode avoid recomputing state it
+/// already knows.
+struct DedupIndex {
+    conn: rusqlite::Connection,
+    /// Canonicalized location of the index file itself, so callers can keep
+    /// it out of the set of files being organized.
+    db_path: PathBuf,
+}
+
+impl DedupIndex {
+    /// Opens (creating if needed) the SQLite file at `db_path` and ensures
+    /// the `files` table exists.
+    fn open(db_path: &Path) -> rusqlite::Result<Self> {
+        let conn = rusqlite::Connection::open(db_path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS files (
+                canonical_path TEXT PRIMARY KEY,
+                size           INTEGER NOT NULL,
+                hash           TEXT NOT NULL,
+                last_modified  INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        let db_path = resolve_key_path(db_path);
+        Ok(Self { conn, db_path })
+    }
+
+    /// Whether `path` is the index's own backing file — used to keep the
+    /// database out of the set of files being organized.
+    fn owns_path(&self, path: &Path) -> bool {
+        resolve_key_path(path) == self.db_path
+    }
+
+    /// Loads every previously recorded hash, grouped by size, so a fresh
+    /// run can recognize duplicates of files organized in earlier runs
+    /// without re-hashing the whole index up front.
+    fn known_hashes_by_size(&self) -> rusqlite::Result<HashMap<u64, Vec<u128>>> {
+        let mut statement = self.conn.prepare("SELECT size, hash FROM files")?;
+        let rows = statement.query_map([], |row| {
+            let size: i64 = row.get(0)?;
+            let hash: String = row.get(1)?;
+            Ok((size as u64, hash))
+        })?;
+
+        let mut known: HashMap<u64, Vec<u128>> = HashMap::new();
+        for row in rows {
+            let (size, hash_hex) = row?;
+            if let Ok(hash) = u128::from_str_radix(&hash_hex, 16) {
+                known.entry(size).or_default().push(hash);
+            }
+        }
+        Ok(known)
+    }
+
+    /// Inserts or updates the row for `canonical_path`.
+    fn upsert(&self, canonical_path: &Path, size: u64, hash: u128, last_modified: i64) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO files (canonical_path, size, hash, last_modified)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(canonical_path) DO UPDATE SET
+                size = excluded.size,
+                hash = excluded.hash,
+                last_modified = excluded.last_modified",
+            rusqlite::params![
+                canonical_path.to_string_lossy(),
+                size as i64,
+                format!("{:032x}", hash),
+                last_modified
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the row for `canonical_path`, if any — used when a file is
+    /// deleted or renamed away so the index stays consistent with disk.
+    fn remove(&self, canonical_path: &Path) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "DELETE FROM files WHERE canonical_path = ?1",
+            rusqlite::params![canonical_path.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+}
+
+fn sqlite_err_to_io(err: rusqlite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// A stable per-session key for a path: `fs::canonicalize` when the entry
+/// still exists (resolving symlinks, matching the same file reached via
+/// different relative paths), falling back to a plain absolute path when
+/// it doesn't (e.g. a `Remove` event fires after the file is already gone).
+fn resolve_key_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        let mut absolute = std::env::current_dir().unwrap_or_default();
+        absolute.push(path);
+        absolute
+    })
+}
+
+/// Hashes, stats, and records `new_location` in the dedup index, removing
+/// the stale row for wherever the file used to live. A no-op when `index`
+/// is `None`.
+fn index_moved_file(
+    index: Option<&DedupIndex>,
+    old_key: Option<PathBuf>,
+    new_location: &Path,
+) -> io::Result<()> {
+    let index = match index {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+
+    if let Some(old_key) = old_key {
+        let _ = index.remove(&old_key); // Best-effort; a missing row is fine
+    }
+
+    let metadata = fs::metadata(new_location)?;
+    let hash = hash_file_xxh3(new_location)?;
+    let last_modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let new_key = resolve_key_path(new_location);
+    index
+        .upsert(&new_key, metadata.len(), hash, last_modified)
+        .map_err(sqlite_err_to_io)
+}
+
 ///  Organizes files by type (images, videos, etc.) and modified date
-/// Also detects duplicates based on file hash
-fn organize_files(folder_path: &str) -> io::Result<()> {
+/// Also detects duplicates using a cheap-first, multi-stage comparison
+fn organize_files(
+    folder_path: &str,
+    format_template: &str,
+    date_source: DateSource,
+    classifier: Option<&Classifier>,
+    index: Option<&DedupIndex>,
+) -> io::Result<()> {
     let all_files = fs::read_dir(folder_path)?; // Read all entries in the directory
-    let mut seen_hashes = HashSet::new(); // Track hashes to detect duplicates
 
+    let mut paths = Vec::new();
     for entry in all_files {
         let entry = entry?;
         let path = entry.path();
+        if path.is_file() && !index.is_some_and(|index| index.owns_path(&path)) {
+            paths.push(path);
+        }
+    }
+
+    organize_paths(folder_path, paths, format_template, date_source, classifier, index)
+}
+
+/// Same as `organize_files`, but over an explicit set of paths instead of a
+/// full directory listing. Used by `watch_folder` so a debounced burst of
+/// events only touches the files that actually changed.
+fn organize_paths(
+    folder_path: &str,
+    paths: Vec<PathBuf>,
+    format_template: &str,
+    date_source: DateSource,
+    classifier: Option<&Classifier>,
+    index: Option<&DedupIndex>,
+) -> io::Result<()> {
+    // Files the config's allowlist excludes must be left untouched entirely,
+    // so filter them out before dedup runs — otherwise an excluded file could
+    // still get matched as a content duplicate and moved into `duplicates/`.
+    let mut organizable = Vec::with_capacity(paths.len());
+    for path in paths {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        if let Some(category) = classify_extension(classifier, &extension) {
+            organizable.push((path, extension, category));
+        }
+    }
+
+    let known_by_size = match index {
+        Some(index) => index.known_hashes_by_size().map_err(sqlite_err_to_io)?,
+        None => HashMap::new(),
+    };
+    let organizable_paths: Vec<PathBuf> =
+        organizable.iter().map(|(path, _, _)| path.clone()).collect();
+    let duplicates = find_duplicates(&organizable_paths, &known_by_size)?;
+
+    for (path, extension, target_folder) in organizable {
+        let old_key = index.map(|_| resolve_key_path(&path));
+
+        //  Step 1: Check whether this file was flagged as a duplicate
+        if duplicates.contains(&path) {
+            println!(" Duplicate found: {:?}", path.file_name().unwrap());
+            if let Some(new_location) = move_to_folder(&path, folder_path, "duplicates")? {
+                index_moved_file(index, old_key, &new_location)?;
+            }
+            continue;
+        }
 
-        if path.is_file() {
-            //  Step 1: Hash the file to check for duplicates
-            let hash = hash_file(&path)?;
-            if seen_hashes.contains(&hash) {
-                println!(" Duplicate found: {:?}", path.file_name().unwrap());
-                move_to_folder(&path, folder_path, "duplicates")?;
-                continue;
-            } else {
-                seen_hashes.insert(hash);
+        //  Step 3: Resolve the destination subpath from the format template
+        let metadata = fs::metadata(&path)?;
+        let date = resolve_file_date(&path, &metadata, date_source)?;
+        let destination = match date {
+            Some(date) => {
+                resolve_destination(format_template, &path, &target_folder, &extension, date)
             }
+            None => format!("{}/unknown-date", target_folder),
+        };
 
-            // 🔍 Step 2: Identify file type by extension
-            let extension = path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("")
-                .to_lowercase();
-
-            // Match extension to a target folder
-            let target_folder = match extension.as_str() {
-                "jpg" | "jpeg" | "png" | "bmp" | "tiff" => "images",
-                "gif" => "gifs",
-                "mp4" | "mov" | "avi" | "mkv" => "videos",
-                "mp3" | "wav" | "flac" => "audio",
-                "pdf" | "docx" | "txt" => "documents",
-                "zip" | "rar" | "7z" => "archives",
-                _ => "others",
-            };
-
-            //  Step 3: Create date-based subfolder like images/2025-10-25
-            let metadata = fs::metadata(&path)?;
-            let modified_time: DateTime<Local> = metadata.modified()?.into();
-            let date_str = modified_time.format("%Y-%m-%d").to_string();
-            let date_folder = format!("{}/{}", target_folder, date_str);
-
-            //  Step 4: Move file to new location
-            move_to_folder(&path, folder_path, &date_folder)?;
+        //  Step 4: Move file to new location
+        if let Some(new_location) = move_to_folder(&path, folder_path, &destination)? {
+            index_moved_file(index, old_key, &new_location)?;
         }
     }
     Ok(())
 }
 
-///  Moves a file into its destination folder
-/// If folder doesn’t exist, it creates it
-fn move_to_folder(path: &Path, base_folder: &str, subfolder: &str) -> io::Result<()> {
-    // Combine base folder + subfolder name → final path
-    let path_for_new_folder = Path::new(base_folder).join(subfolder);
-    if !path_for_new_folder.exists() {
-        fs::create_dir_all(&path_for_new_folder)?; // Create nested directories if missing
+/// Finds duplicates among `paths` using a three-stage pipeline, each stage
+/// only run on the candidates the previous stage couldn't rule out:
+///
+/// 1. Group by exact file size (`metadata.len()`) — different sizes can
+///    never be duplicates.
+/// 2. Within a size bucket, split further by a cheap partial fingerprint
+///    (the first and last `PARTIAL_FINGERPRINT_CHUNK` bytes).
+/// 3. Only files still colliding after that get a full-content hash.
+///
+/// Singleton buckets at any stage are skipped entirely, unless
+/// `known_by_size` (hashes of files organized in a previous session, from
+/// the persistent dedup index) has an entry for that size — in which case
+/// the lone file still needs a full hash to check against it.
+fn find_duplicates(
+    paths: &[PathBuf],
+    known_by_size: &HashMap<u64, Vec<u128>>,
+) -> io::Result<HashSet<PathBuf>> {
+    let mut size_buckets: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        let size = fs::metadata(path)?.len();
+        size_buckets.entry(size).or_default().push(path.clone());
     }
 
-    let file_name = path.file_name().unwrap(); // Extract file name
-    let new_location = path_for_new_folder.join(file_name);
+    let mut duplicates = HashSet::new();
 
-    // Only move if file doesn’t already exist in destination
-    if !new_location.exists() {
-        fs::rename(path, &new_location)?;
-        println!(" Moved {:?} → {:?}", file_name, new_location);
+    for (size, bucket) in size_buckets {
+        let known_hashes = known_by_size.get(&size);
+        if bucket.len() < 2 && known_hashes.is_none() {
+            continue; // Only file of this size, and none known from before — can't be a duplicate
+        }
+
+        if bucket.len() < 2 {
+            // No same-size sibling in this run, but the index has seen this
+            // size before — hash the one file and check against it directly
+            let path = &bucket[0];
+            let hash = hash_file_xxh3(path)?;
+            if known_hashes.unwrap().contains(&hash) {
+                duplicates.insert(path.clone());
+            }
+            continue;
+        }
+
+        let mut fingerprint_buckets: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for path in bucket {
+            let fingerprint = partial_fingerprint(&path, size)?;
+            fingerprint_buckets.entry(fingerprint).or_default().push(path);
+        }
+
+        for (_, candidates) in fingerprint_buckets {
+            if candidates.len() < 2 && known_hashes.is_none() {
+                continue; // Fingerprint narrowed this down to a single, never-seen file
+            }
+
+            let mut seen_hashes: HashSet<u128> =
+                known_hashes.map(|hashes| hashes.iter().copied().collect()).unwrap_or_default();
+            for path in candidates {
+                let hash = hash_file_xxh3(&path)?;
+                if seen_hashes.contains(&hash) {
+                    duplicates.insert(path);
+                } else {
+                    seen_hashes.insert(hash);
+                }
+            }
+        }
     }
-    Ok(())
+
+    Ok(duplicates)
+}
+
+/// Cheap pre-filter for dedup candidates: combines the file's length with an
+/// xxh3_64 digest of its first and last `PARTIAL_FINGERPRINT_CHUNK` bytes.
+/// Files that differ here can never be duplicates, so a full hash is never
+/// needed for them.
+fn partial_fingerprint(path: &Path, size: u64) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = Vec::with_capacity(PARTIAL_FINGERPRINT_CHUNK * 2);
+
+    let mut head = [0u8; PARTIAL_FINGERPRINT_CHUNK];
+    let head_read = file.read(&mut head)?;
+    buffer.extend_from_slice(&head[..head_read]);
+
+    if size as usize > PARTIAL_FINGERPRINT_CHUNK {
+        let tail_start = size.saturating_sub(PARTIAL_FINGERPRINT_CHUNK as u64);
+        file.seek(SeekFrom::Start(tail_start))?;
+        let mut tail = [0u8; PARTIAL_FINGERPRINT_CHUNK];
+        let tail_read = file.read(&mut tail)?;
+        buffer.extend_from_slice(&tail[..tail_read]);
+    }
+
+    buffer.extend_from_slice(&size.to_le_bytes());
+    Ok(xxh3_64(&buffer))
 }
 
-/// Hash the contents of a file using SHA256
-/// Used to detect duplicates
-fn hash_file(path: &Path) -> io::Result<String> {
+/// Hash the full contents of a file using xxh3_128.
+/// Used to confirm duplicates once size and partial fingerprint collide.
+/// Streams through the incremental `Xxh3` hasher instead of buffering the
+/// whole file into memory — large media files shouldn't need their full
+/// contents resident in RAM just to be hashed.
+/// Not cryptographically secure, but that's not needed for dedup — only
+/// collision avoidance on accidental, not adversarial, input matters here.
+fn hash_file_xxh3(path: &Path) -> io::Result<u128> {
     let mut file = File::open(path)?;
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; 4096]; // Read in chunks to handle large files
+    let mut buffer = [0; 65536]; // Read in larger chunks since xxh3 is cheap per byte
+    let mut hasher = Xxh3::new();
 
     loop {
         let bytes_read = file.read(&mut buffer)?;
@@ -136,17 +583,159 @@ fn hash_file(path: &Path) -> io::Result<String> {
         hasher.update(&buffer[..bytes_read]);
     }
 
-    // Convert hash result to hex string
-    Ok(format!("{:x}", hasher.finalize()))
+    Ok(hasher.digest128())
+}
+
+/// Regexes tried in order against a file's name to recover a capture date,
+/// covering the naming schemes of common cameras and screenshot tools
+/// before falling back to a generic `YYYYMMDD`-ish scan.
+static FILENAME_DATE_PATTERNS: Lazy<Vec<Regex>> = Lazy::new(|| {
+    vec![
+        Regex::new(r"^PXL_(\d{4})(\d{2})(\d{2})").unwrap(),
+        Regex::new(r"^IMG_(\d{4})(\d{2})(\d{2})").unwrap(),
+        Regex::new(r"^Screenshot_(\d{4})-(\d{2})-(\d{2})").unwrap(),
+        Regex::new(r"(\d{4})[-_]?(\d{2})[-_]?(\d{2})").unwrap(),
+    ]
+});
+
+/// Tries each pattern in `FILENAME_DATE_PATTERNS` against `file_name`,
+/// returning the first match whose captured components form a real
+/// calendar date (month 1–12, day valid for that month).
+fn extract_date_from_filename(file_name: &str) -> Option<NaiveDate> {
+    for pattern in FILENAME_DATE_PATTERNS.iter() {
+        let captures = match pattern.captures(file_name) {
+            Some(captures) => captures,
+            None => continue,
+        };
+
+        let year: i32 = captures[1].parse().ok()?;
+        let month: u32 = captures[2].parse().ok()?;
+        let day: u32 = captures[3].parse().ok()?;
+
+        if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+            return Some(date);
+        }
+    }
+    None
+}
+
+/// Resolves the date to file a path under, per `date_source`. Returns
+/// `None` only for `DateSource::Filename` when no pattern matched — callers
+/// use that to route the file to an `unknown-date` folder instead of
+/// guessing from mtime.
+fn resolve_file_date(
+    path: &Path,
+    metadata: &fs::Metadata,
+    date_source: DateSource,
+) -> io::Result<Option<NaiveDate>> {
+    let mtime_date = || -> io::Result<NaiveDate> {
+        let modified: DateTime<Local> = metadata.modified()?.into();
+        Ok(modified.date_naive())
+    };
+
+    match date_source {
+        DateSource::Mtime => Ok(Some(mtime_date()?)),
+        DateSource::Filename => {
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            Ok(extract_date_from_filename(file_name))
+        }
+        DateSource::FilenameThenMtime => {
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            match extract_date_from_filename(file_name) {
+                Some(date) => Ok(Some(date)),
+                None => Ok(Some(mtime_date()?)),
+            }
+        }
+    }
+}
+
+/// Resolves a destination template like `{category}/{year}/{month:long}/{ext}`
+/// into a concrete subpath for a file, substituting each placeholder:
+/// `{category}`, `{ext}`, `{year}`, `{month}` (numeric), `{month:long}`
+/// (e.g. `08 - August`), `{day}`, and `{name}` (file stem). Every `/` left in
+/// the result becomes a nested directory when `move_to_folder` creates it.
+fn resolve_destination(
+    template: &str,
+    path: &Path,
+    category: &str,
+    extension: &str,
+    date: NaiveDate,
+) -> String {
+    let year = date.format("%Y").to_string();
+    let month = date.format("%m").to_string();
+    let month_long = format!("{} - {}", month, date.format("%B"));
+    let day = date.format("%d").to_string();
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    template
+        .replace("{category}", category)
+        .replace("{month:long}", &month_long)
+        .replace("{month}", &month)
+        .replace("{year}", &year)
+        .replace("{day}", &day)
+        .replace("{ext}", extension)
+        .replace("{name}", &name)
+}
+
+/// Moves a file into its destination folder, creating it if it doesn't
+/// exist. Returns the new location, or `None` if the move was skipped
+/// because a file already sits at the destination.
+fn move_to_folder(path: &Path, base_folder: &str, subfolder: &str) -> io::Result<Option<PathBuf>> {
+    // Combine base folder + subfolder name → final path
+    let path_for_new_folder = Path::new(base_folder).join(subfolder);
+    if !path_for_new_folder.exists() {
+        fs::create_dir_all(&path_for_new_folder)?; // Create nested directories if missing
+    }
+
+    let file_name = path.file_name().unwrap(); // Extract file name
+    let new_location = path_for_new_folder.join(file_name);
+
+    // Only move if file doesn’t already exist in destination
+    if new_location.exists() {
+        return Ok(None);
+    }
+    fs::rename(path, &new_location)?;
+    println!(" Moved {:?} → {:?}", file_name, new_location);
+    Ok(Some(new_location))
 }
 
-fn watch_folder(folder_path: &str) -> io::Result<()> {
+/// How long to wait after the last filesystem event before reorganizing, so
+/// a burst of events (e.g. a multi-file download) coalesces into one pass
+/// instead of one `organize_paths` call per event.
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(500);
+
+fn watch_folder(
+    folder_path: &str,
+    format_template: &str,
+    date_source: DateSource,
+    classifier: Option<&Classifier>,
+    poll_interval_secs: u64,
+    index: Option<&DedupIndex>,
+) -> io::Result<()> {
     // Create an mpsc channel to receive file system events
     let (tx, rx) = mpsc::channel();
 
-    //  New API: Pass config instead of Duration
-    let mut watcher =
-        RecommendedWatcher::new(tx, Config::default()).expect("Failed to initialize watcher");
+    //  The recommended backend can itself be a PollWatcher on platforms
+    //  where inotify/FSEvents/etc. aren't available — in that case build the
+    //  PollWatcher explicitly so we can set our own interval instead of
+    //  notify's default. `kind()` is fixed per platform: a native backend
+    //  that starts but delivers no events (e.g. on a network share) is not
+    //  detected, and a native backend that fails to start is not retried
+    //  as polling.
+    let mut watcher: Box<dyn Watcher> = if RecommendedWatcher::kind() == WatcherKind::PollWatcher {
+        println!(
+            "Native watch backend unavailable; polling every {}s",
+            poll_interval_secs
+        );
+        let poll_config = Config::default().with_poll_interval(Duration::from_secs(poll_interval_secs));
+        Box::new(PollWatcher::new(tx, poll_config).expect("Failed to initialize poll watcher"))
+    } else {
+        Box::new(RecommendedWatcher::new(tx, Config::default()).expect("Failed to initialize watcher"))
+    };
 
     //  Convert folder_path to &Path
     watcher
@@ -155,24 +744,379 @@ fn watch_folder(folder_path: &str) -> io::Result<()> {
 
     println!("👀 Watching folder: {}", folder_path);
 
-    // Loop to handle events
-    for res in rx {
-        match res {
-            Ok(Event { kind, .. }) => {
-                // Only trigger on file creation or modification events
-                if matches!(
-                    kind,
-                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_))
+    // Paths touched since the last flush, collected across a debounce
+    // window instead of triggering a reorganization per event
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+
+    loop {
+        match rx.recv_timeout(DEBOUNCE_WINDOW) {
+            Ok(Ok(event)) => record_event(&mut pending, &event, index),
+            Ok(Err(e)) => println!(" Watch error: {:?}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending.is_empty() {
+                    continue;
+                }
+                // Quiet period elapsed — only reorganize the affected paths,
+                // not the whole directory, skip any that moved away, and
+                // skip the dedup index's own backing file so its SQLite
+                // writes don't get picked up and moved on the next flush
+                let paths: Vec<PathBuf> = pending
+                    .drain()
+                    .filter(|path| {
+                        path.is_file() && !index.is_some_and(|index| index.owns_path(path))
+                    })
+                    .collect();
+                if paths.is_empty() {
+                    continue;
+                }
+                println!(" {} file(s) changed. Reorganizing...", paths.len());
+                if let Err(e) = organize_paths(
+                    folder_path,
+                    paths,
+                    format_template,
+                    date_source,
+                    classifier,
+                    index,
                 ) {
-                    println!(" New file detected. Reorganizing...");
-                    if let Err(e) = organize_files(folder_path) {
-                        eprintln!(" Error during reorganization: {:?}", e);
-                    }
+                    eprintln!(" Error during reorganization: {:?}", e);
                 }
             }
-            Err(e) => println!(" Watch error: {:?}", e),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
         }
     }
 
     Ok(())
 }
+
+/// Folds one filesystem event into the pending set: `Create`/`Modify(Data)`
+/// add the affected paths, `Remove` drops them (nothing to organize at a
+/// path that no longer exists), and `Modify(Name)` (renames) adds the path
+/// back only if it still exists, otherwise drops it — so a file renamed
+/// away mid-burst isn't reprocessed at its old location.
+fn record_event(pending: &mut HashSet<PathBuf>, event: &Event, index: Option<&DedupIndex>) {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_)) => {
+            pending.extend(event.paths.iter().cloned());
+        }
+        EventKind::Modify(ModifyKind::Name(_)) => {
+            for path in &event.paths {
+                if path.exists() {
+                    pending.insert(path.clone());
+                } else {
+                    pending.remove(path);
+                    remove_from_index(index, path);
+                }
+            }
+        }
+        EventKind::Remove(_) => {
+            for path in &event.paths {
+                pending.remove(path);
+                remove_from_index(index, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Deletes the index row for a path that just disappeared from disk
+/// (removed, or renamed away). A no-op when `index` is `None`. The watch is
+/// non-recursive, so only entries directly inside the watched folder reach
+/// here; rows for files already moved into its subfolders are not removed
+/// when those files are deleted or renamed.
+fn remove_from_index(index: Option<&DedupIndex>, path: &Path) {
+    if let Some(index) = index {
+        let key = resolve_key_path(path);
+        let _ = index.remove(&key); // Best-effort; nothing to do on failure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fileorganizer_test_{}_{}.db", std::process::id(), name))
+    }
+
+    fn temp_config_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fileorganizer_test_{}_{}.toml", std::process::id(), name))
+    }
+
+    /// Unlike `temp_config_path`, keeps `name` (including its extension)
+    /// verbatim so tests that care about the file's own name/extension
+    /// (date-from-filename parsing, destination templates) get what they ask for.
+    fn temp_file_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fileorganizer_test_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn load_classifier_preserves_declared_category_order() {
+        let config_path = temp_config_path("order");
+        fs::write(
+            &config_path,
+            r#"
+            [categories]
+            photos = ["^jpe?g$"]
+            images = ["^jpe?g$"]
+            "#,
+        )
+        .expect("write config");
+
+        let classifier = load_classifier(config_path.to_str().unwrap()).expect("load classifier");
+        assert_eq!(
+            classifier.rules.iter().map(|(category, _)| category.as_str()).collect::<Vec<_>>(),
+            vec!["photos", "images"]
+        );
+
+        let _ = fs::remove_file(&config_path);
+    }
+
+    #[test]
+    fn classify_extension_picks_first_matching_rule_deterministically() {
+        let classifier = Classifier {
+            rules: vec![
+                ("photos".to_string(), Regex::new("^jpe?g$").unwrap()),
+                ("images".to_string(), Regex::new("^jpe?g$").unwrap()),
+            ],
+            allowlist: None,
+        };
+
+        assert_eq!(classify_extension(Some(&classifier), "jpg"), Some("photos".to_string()));
+        assert_eq!(classify_extension(Some(&classifier), "png"), Some("others".to_string()));
+    }
+
+    #[test]
+    fn classify_extension_respects_allowlist() {
+        let classifier = Classifier {
+            rules: vec![("images".to_string(), Regex::new("^jpe?g$").unwrap())],
+            allowlist: Some(HashSet::from(["jpg".to_string()])),
+        };
+
+        assert_eq!(classify_extension(Some(&classifier), "jpg"), Some("images".to_string()));
+        assert_eq!(classify_extension(Some(&classifier), "png"), None);
+    }
+
+    #[test]
+    fn extract_date_from_filename_matches_pxl_pattern() {
+        let date = extract_date_from_filename("PXL_20240115_103000123.jpg").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2024, 1, 15).unwrap());
+    }
+
+    #[test]
+    fn extract_date_from_filename_matches_img_pattern() {
+        let date = extract_date_from_filename("IMG_20230704.heic").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2023, 7, 4).unwrap());
+    }
+
+    #[test]
+    fn extract_date_from_filename_matches_screenshot_pattern() {
+        let date = extract_date_from_filename("Screenshot_2022-12-25-09-00-00.png").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2022, 12, 25).unwrap());
+    }
+
+    #[test]
+    fn extract_date_from_filename_matches_generic_pattern() {
+        assert_eq!(
+            extract_date_from_filename("backup_2021_06_09_final.zip"),
+            Some(NaiveDate::from_ymd_opt(2021, 6, 9).unwrap())
+        );
+        assert_eq!(
+            extract_date_from_filename("20210609-final.zip"),
+            Some(NaiveDate::from_ymd_opt(2021, 6, 9).unwrap())
+        );
+    }
+
+    #[test]
+    fn extract_date_from_filename_rejects_invalid_calendar_dates() {
+        // Month 13 and day 32 aren't real calendar dates, so these should
+        // fall through every pattern and return None
+        assert_eq!(extract_date_from_filename("IMG_20231399.jpg"), None);
+        assert_eq!(extract_date_from_filename("PXL_20240132.jpg"), None);
+    }
+
+    #[test]
+    fn extract_date_from_filename_returns_none_without_a_match() {
+        assert_eq!(extract_date_from_filename("vacation-photo.jpg"), None);
+    }
+
+    #[test]
+    fn resolve_file_date_mtime_ignores_filename() {
+        let path = temp_file_path("IMG_20000101_mtime_only.jpg");
+        fs::write(&path, b"data").expect("write temp file");
+        let metadata = fs::metadata(&path).expect("stat temp file");
+        let modified: DateTime<Local> = metadata.modified().expect("read mtime").into();
+
+        let date = resolve_file_date(&path, &metadata, DateSource::Mtime).expect("resolve date");
+        assert_eq!(date, Some(modified.date_naive()));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn resolve_file_date_filename_only_returns_none_without_a_match() {
+        let path = temp_file_path("no-date-in-name.jpg");
+        fs::write(&path, b"data").expect("write temp file");
+        let metadata = fs::metadata(&path).expect("stat temp file");
+
+        let date = resolve_file_date(&path, &metadata, DateSource::Filename).expect("resolve date");
+        assert_eq!(date, None);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn resolve_file_date_filename_then_mtime_falls_back() {
+        let path = temp_file_path("no-date-in-name-fallback.jpg");
+        fs::write(&path, b"data").expect("write temp file");
+        let metadata = fs::metadata(&path).expect("stat temp file");
+
+        let date = resolve_file_date(&path, &metadata, DateSource::FilenameThenMtime)
+            .expect("resolve date");
+        assert!(date.is_some());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn resolve_destination_substitutes_default_template() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let path = Path::new("vacation.jpg");
+
+        let destination =
+            resolve_destination(DEFAULT_DEST_TEMPLATE, path, "images", "jpg", date);
+
+        assert_eq!(destination, "images/2024-03-09");
+    }
+
+    #[test]
+    fn resolve_destination_substitutes_every_placeholder() {
+        let date = NaiveDate::from_ymd_opt(2024, 8, 1).unwrap();
+        let path = Path::new("report.final.pdf");
+
+        let destination = resolve_destination(
+            "{category}/{year}/{month:long}/{day}/{name}.{ext}",
+            path,
+            "documents",
+            "pdf",
+            date,
+        );
+
+        assert_eq!(destination, "documents/2024/08 - August/01/report.final.pdf");
+    }
+
+    #[test]
+    fn resolve_destination_uses_file_stem_for_name() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let path = Path::new("archive.tar.gz");
+
+        let destination = resolve_destination("{name}", path, "others", "gz", date);
+
+        assert_eq!(destination, "archive.tar");
+    }
+
+    #[test]
+    fn find_duplicates_detects_identical_files_within_one_run() {
+        let a = temp_file_path("dup_a.bin");
+        let b = temp_file_path("dup_b.bin");
+        fs::write(&a, b"same contents").unwrap();
+        fs::write(&b, b"same contents").unwrap();
+
+        let duplicates = find_duplicates(&[a.clone(), b.clone()], &HashMap::new()).unwrap();
+
+        assert_eq!(duplicates.len(), 1);
+        assert!(duplicates.contains(&a) || duplicates.contains(&b));
+
+        let _ = fs::remove_file(&a);
+        let _ = fs::remove_file(&b);
+    }
+
+    #[test]
+    fn find_duplicates_ignores_same_size_files_with_different_contents() {
+        let a = temp_file_path("nodup_a.bin");
+        let b = temp_file_path("nodup_b.bin");
+        fs::write(&a, b"aaaaaaaaaa").unwrap();
+        fs::write(&b, b"bbbbbbbbbb").unwrap();
+
+        let duplicates = find_duplicates(&[a.clone(), b.clone()], &HashMap::new()).unwrap();
+
+        assert!(duplicates.is_empty());
+
+        let _ = fs::remove_file(&a);
+        let _ = fs::remove_file(&b);
+    }
+
+    #[test]
+    fn find_duplicates_matches_against_known_hashes_from_a_prior_run() {
+        let path = temp_file_path("singleton.bin");
+        fs::write(&path, b"already indexed").unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+        let hash = hash_file_xxh3(&path).unwrap();
+
+        let mut known_by_size = HashMap::new();
+        known_by_size.insert(size, vec![hash]);
+
+        let duplicates = find_duplicates(&[path.clone()], &known_by_size).unwrap();
+
+        assert!(duplicates.contains(&path));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn partial_fingerprint_differs_for_different_head_bytes() {
+        let a = temp_file_path("fingerprint_a.bin");
+        let b = temp_file_path("fingerprint_b.bin");
+        fs::write(&a, b"head-a-bytes").unwrap();
+        fs::write(&b, b"head-b-bytes").unwrap();
+
+        let size = fs::metadata(&a).unwrap().len();
+        let fingerprint_a = partial_fingerprint(&a, size).unwrap();
+        let fingerprint_b = partial_fingerprint(&b, size).unwrap();
+
+        assert_ne!(fingerprint_a, fingerprint_b);
+
+        let _ = fs::remove_file(&a);
+        let _ = fs::remove_file(&b);
+    }
+
+    #[test]
+    fn dedup_index_round_trips_hashes_by_size() {
+        let db_path = temp_db_path("round_trip");
+        let _ = fs::remove_file(&db_path);
+        let index = DedupIndex::open(&db_path).expect("open index");
+
+        index
+            .upsert(Path::new("/tmp/a.jpg"), 1024, 0xABCDEF, 1_700_000_000)
+            .expect("upsert a");
+        index
+            .upsert(Path::new("/tmp/b.jpg"), 1024, 0x123456, 1_700_000_001)
+            .expect("upsert b");
+        index
+            .upsert(Path::new("/tmp/c.png"), 2048, 0xABCDEF, 1_700_000_002)
+            .expect("upsert c");
+
+        let known = index.known_hashes_by_size().expect("load known hashes");
+        let mut size_1024 = known.get(&1024).cloned().unwrap_or_default();
+        size_1024.sort();
+        assert_eq!(size_1024, vec![0x123456, 0xABCDEF]);
+        assert_eq!(known.get(&2048), Some(&vec![0xABCDEFu128]));
+
+        index.remove(Path::new("/tmp/a.jpg")).expect("remove a");
+        let known = index.known_hashes_by_size().expect("reload known hashes");
+        assert_eq!(known.get(&1024), Some(&vec![0x123456u128]));
+
+        let _ = fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn dedup_index_owns_path_matches_its_own_db_file() {
+        let db_path = temp_db_path("owns_path");
+        let _ = fs::remove_file(&db_path);
+        let index = DedupIndex::open(&db_path).expect("open index");
+
+        assert!(index.owns_path(&db_path));
+        assert!(!index.owns_path(Path::new("/tmp/unrelated-file.jpg")));
+
+        let _ = fs::remove_file(&db_path);
+    }
+}